@@ -0,0 +1,288 @@
+//! SLIP-132 extended key version bytes.
+//!
+//! Standard BIP32 serialization always emits the `xpub`/`xprv` version bytes, which most
+//! wallets and hardware signers read back as "this key signs legacy P2PKH", even when an
+//! `XKeyInfo`'s `Hint` says otherwise. [SLIP-132](https://github.com/satoshilabs/slips/blob/master/slip-0132.md)
+//! defines alternate version bytes that advertise the intended script type instead:
+//! `ypub`/`yprv` for P2WPKH-in-P2SH, `zpub`/`zprv` for native P2WPKH, and the testnet
+//! `tpub`/`upub`/`vpub` (and `tprv`/`uprv`/`vprv`) families. This module maps an `XKeyInfo`'s
+//! `Hint` to the right version bytes on encode, and recovers the `Hint` from the version bytes
+//! on decode, so a parsed `DerivedXPub` round-trips with its intended script type.
+
+use std::convert::TryInto;
+
+use sha2::{Digest, Sha256};
+
+use crate::model::{Hint, KeyFingerprint};
+use crate::xkeys::XKeyInfo;
+
+/// Errors decoding a SLIP-132 extended key version prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slip132Error {
+    /// The 4-byte prefix isn't one of the `xpub`/`ypub`/`zpub` (or testnet `tpub`/`upub`/`vpub`)
+    /// families this module knows about.
+    UnknownVersionBytes([u8; 4]),
+    /// The string wasn't valid base58, or didn't checksum-verify.
+    BadBase58,
+    /// The string checksum-verified but its payload (checksum stripped) wasn't 82 bytes (4-byte
+    /// version + 78-byte body), so it can't be a serialized extended key.
+    BadLength,
+}
+
+/// Which network a serialized extended key's version bytes target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    /// Mainnet: `xpub`/`ypub`/`zpub` and their private counterparts.
+    Main,
+    /// Testnet (and regtest/signet, which reuse the same prefixes): `tpub`/`upub`/`vpub`.
+    Test,
+}
+
+/// The 4-byte version prefix written at the start of a Base58Check-encoded extended key,
+/// chosen per SLIP-132 from the key's `Hint`, `Network`, and whether it's a private or public
+/// key.
+pub fn version_bytes(hint: Hint, network: Network, privkey: bool) -> [u8; 4] {
+    let version: u32 = match (hint, network, privkey) {
+        (Hint::Legacy, Network::Main, false) => 0x0488_B21E,
+        (Hint::Legacy, Network::Main, true) => 0x0488_ADE4,
+        (Hint::Compatibility, Network::Main, false) => 0x049D_7CB2,
+        (Hint::Compatibility, Network::Main, true) => 0x049D_7878,
+        (Hint::SegWit, Network::Main, false) => 0x04B2_4746,
+        (Hint::SegWit, Network::Main, true) => 0x04B2_430C,
+        (Hint::Legacy, Network::Test, false) => 0x0435_87CF,
+        (Hint::Legacy, Network::Test, true) => 0x0435_8394,
+        (Hint::Compatibility, Network::Test, false) => 0x044A_5262,
+        (Hint::Compatibility, Network::Test, true) => 0x044A_4E28,
+        (Hint::SegWit, Network::Test, false) => 0x045F_1CF6,
+        (Hint::SegWit, Network::Test, true) => 0x045F_18BC,
+    };
+    version.to_be_bytes()
+}
+
+/// Recover the `Hint`, `Network`, and private/public-ness a 4-byte version prefix encodes: the
+/// inverse of `version_bytes`. Errors on any prefix SLIP-132 doesn't define.
+pub fn hint_from_version_bytes(version: [u8; 4]) -> Result<(Hint, Network, bool), Slip132Error> {
+    match u32::from_be_bytes(version) {
+        0x0488_B21E => Ok((Hint::Legacy, Network::Main, false)),
+        0x0488_ADE4 => Ok((Hint::Legacy, Network::Main, true)),
+        0x049D_7CB2 => Ok((Hint::Compatibility, Network::Main, false)),
+        0x049D_7878 => Ok((Hint::Compatibility, Network::Main, true)),
+        0x04B2_4746 => Ok((Hint::SegWit, Network::Main, false)),
+        0x04B2_430C => Ok((Hint::SegWit, Network::Main, true)),
+        0x0435_87CF => Ok((Hint::Legacy, Network::Test, false)),
+        0x0435_8394 => Ok((Hint::Legacy, Network::Test, true)),
+        0x044A_5262 => Ok((Hint::Compatibility, Network::Test, false)),
+        0x044A_4E28 => Ok((Hint::Compatibility, Network::Test, true)),
+        0x045F_1CF6 => Ok((Hint::SegWit, Network::Test, false)),
+        0x045F_18BC => Ok((Hint::SegWit, Network::Test, true)),
+        _ => Err(Slip132Error::UnknownVersionBytes(version)),
+    }
+}
+
+/// Serialize an extended key's `XKeyInfo` and raw key material to a SLIP-132 Base58Check string,
+/// choosing `ypub`/`zpub`/etc. version bytes from `info.hint` (rather than always emitting
+/// `xpub`/`xprv`) so the intended script type survives the round trip.
+///
+/// `key_material` is the 33 bytes that follow the chain code in a standard BIP32 serialization:
+/// `0x00 || privkey` for an extended private key, or a compressed pubkey for an extended public
+/// key.
+pub fn encode_extended_key(
+    info: &XKeyInfo,
+    key_material: [u8; 33],
+    network: Network,
+    privkey: bool,
+) -> String {
+    let mut body = Vec::with_capacity(78);
+    body.push(info.depth);
+    body.extend_from_slice(&info.parent.0);
+    body.extend_from_slice(&info.index.to_be_bytes());
+    body.extend_from_slice(&info.chain_code);
+    body.extend_from_slice(&key_material);
+
+    let version = version_bytes(info.hint, network, privkey);
+    base58check_encode(&version, &body)
+}
+
+/// The inverse of `encode_extended_key`: recovers the `XKeyInfo` (with its `Hint` set from the
+/// version bytes, per SLIP-132), the 33-byte key material, and the `Network`/private-ness the
+/// version bytes encode.
+pub fn decode_extended_key(s: &str) -> Result<(XKeyInfo, [u8; 33], Network, bool), Slip132Error> {
+    let data = base58check_decode(s)?;
+    if data.len() != 4 + 78 {
+        return Err(Slip132Error::BadLength);
+    }
+    let (version, body) = data.split_at(4);
+    let (hint, network, privkey) = hint_from_version_bytes(version.try_into().unwrap())?;
+
+    let depth = body[0];
+    let mut parent = [0u8; 4];
+    parent.copy_from_slice(&body[1..5]);
+    let index = u32::from_be_bytes(body[5..9].try_into().unwrap());
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&body[9..41]);
+    let mut key_material = [0u8; 33];
+    key_material.copy_from_slice(&body[41..74]);
+
+    let info = XKeyInfo {
+        depth,
+        parent: KeyFingerprint(parent),
+        index,
+        chain_code,
+        hint,
+    };
+    Ok((info, key_material, network, privkey))
+}
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+fn double_sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    let first = hasher.result();
+    let second = Sha256::digest(&first);
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&second);
+    digest
+}
+
+/// Base58check-encode `version || body`, appending the leading 4 bytes of
+/// `doubleSha256(version || body)` as a checksum.
+fn base58check_encode(version: &[u8; 4], body: &[u8]) -> String {
+    let mut data = Vec::with_capacity(4 + body.len() + 4);
+    data.extend_from_slice(version);
+    data.extend_from_slice(body);
+    let checksum = double_sha256(&data);
+    data.extend_from_slice(&checksum[..4]);
+
+    let zeros = data.iter().take_while(|&&b| b == 0).count();
+    let mut digits: Vec<u8> = vec![0];
+    for &byte in &data {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let mut out: Vec<u8> = std::iter::repeat(BASE58_ALPHABET[0]).take(zeros).collect();
+    out.extend(digits.iter().rev().map(|&d| BASE58_ALPHABET[d as usize]));
+    String::from_utf8(out).expect("base58 alphabet is ASCII")
+}
+
+/// Base58check-decode `s`, verifying its checksum, and returning the full `version || body`
+/// payload (checksum stripped).
+fn base58check_decode(s: &str) -> Result<Vec<u8>, Slip132Error> {
+    let zeros = s.bytes().take_while(|&b| b == BASE58_ALPHABET[0]).count();
+    let mut bytes: Vec<u8> = vec![0];
+    for c in s.bytes() {
+        let mut value = BASE58_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or(Slip132Error::BadBase58)? as u32;
+        for byte in bytes.iter_mut() {
+            value += (*byte as u32) * 58;
+            *byte = (value & 0xff) as u8;
+            value >>= 8;
+        }
+        while value > 0 {
+            bytes.push((value & 0xff) as u8);
+            value >>= 8;
+        }
+    }
+    let mut data: Vec<u8> = std::iter::repeat(0).take(zeros).collect();
+    data.extend(bytes.iter().rev());
+
+    if data.len() < 4 {
+        return Err(Slip132Error::BadBase58);
+    }
+    let (payload, checksum) = data.split_at(data.len() - 4);
+    if double_sha256(payload)[..4] != *checksum {
+        return Err(Slip132Error::BadBase58);
+    }
+    Ok(payload.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Well-known published SLIP-132 mainnet prefixes.
+    const XPUB: u32 = 0x0488_B21E;
+    const YPUB: u32 = 0x049D_7CB2;
+    const ZPUB: u32 = 0x04B2_4746;
+    const TPUB: u32 = 0x0435_87CF;
+    const UPUB: u32 = 0x044A_5262;
+    const VPUB: u32 = 0x045F_1CF6;
+
+    #[test]
+    fn it_matches_published_slip_132_prefixes() {
+        assert_eq!(version_bytes(Hint::Legacy, Network::Main, false), XPUB.to_be_bytes());
+        assert_eq!(version_bytes(Hint::Compatibility, Network::Main, false), YPUB.to_be_bytes());
+        assert_eq!(version_bytes(Hint::SegWit, Network::Main, false), ZPUB.to_be_bytes());
+        assert_eq!(version_bytes(Hint::Legacy, Network::Test, false), TPUB.to_be_bytes());
+        assert_eq!(version_bytes(Hint::Compatibility, Network::Test, false), UPUB.to_be_bytes());
+        assert_eq!(version_bytes(Hint::SegWit, Network::Test, false), VPUB.to_be_bytes());
+    }
+
+    #[test]
+    fn it_round_trips_every_hint_network_privacy_combination() {
+        let hints = [Hint::Legacy, Hint::Compatibility, Hint::SegWit];
+        let networks = [Network::Main, Network::Test];
+
+        for &hint in &hints {
+            for &network in &networks {
+                for &privkey in &[false, true] {
+                    let version = version_bytes(hint, network, privkey);
+                    assert_eq!(hint_from_version_bytes(version), Ok((hint, network, privkey)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_rejects_an_unknown_version_prefix() {
+        let version = 0xDEAD_BEEFu32.to_be_bytes();
+        assert_eq!(hint_from_version_bytes(version), Err(Slip132Error::UnknownVersionBytes(version)));
+    }
+
+    fn hex_33(s: &str) -> [u8; 33] {
+        let mut out = [0u8; 33];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn it_round_trips_a_zpub_with_its_intended_script_type() {
+        // secp256k1's generator point, compressed: a real, on-curve pubkey, stood in for an
+        // account xpub's key material.
+        let key_material =
+            hex_33("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        let info = XKeyInfo {
+            depth: 3,
+            parent: KeyFingerprint([0x11, 0x22, 0x33, 0x44]),
+            index: 0x8000_0000,
+            chain_code: [0x42u8; 32],
+            hint: Hint::SegWit,
+        };
+
+        let encoded = encode_extended_key(&info, key_material, Network::Main, false);
+        assert!(encoded.starts_with("zpub"));
+
+        let (decoded_info, decoded_key, network, privkey) = decode_extended_key(&encoded).unwrap();
+        assert_eq!(decoded_info.depth, info.depth);
+        assert_eq!(decoded_info.parent.0, info.parent.0);
+        assert_eq!(decoded_info.index, info.index);
+        assert_eq!(decoded_info.chain_code, info.chain_code);
+        assert_eq!(decoded_info.hint, info.hint);
+        assert_eq!(&decoded_key[..], &key_material[..]);
+        assert_eq!(network, Network::Main);
+        assert!(!privkey);
+    }
+}
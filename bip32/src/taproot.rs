@@ -0,0 +1,207 @@
+//! BIP340/BIP341 Taproot key-path derivation.
+//!
+//! Taproot output keys are not simply the internal key's x-coordinate: BIP341 tweaks the
+//! internal key with a tagged hash of an (optional) script tree merkle root before it is used as
+//! the output key, so that key-path spends and script-path spends share the same output. This
+//! module adds the x-only pubkey and tweak machinery needed to derive those output keys (and the
+//! matching private scalar) from a `GenericDerivedXPub`/`GenericDerivedXPriv`.
+//!
+//! Note the output of a tweak is *not* a BIP32 node: it has no chain code and nothing should
+//! derive further children from it. `GenericDerivedXPriv::tap_tweak` therefore returns a plain
+//! `GenericPrivkey`, not a fabricated `GenericDerivedXPriv`.
+
+use std::convert::TryInto;
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    curve::model::{ScalarDeserialize, ScalarSerialize, Secp256k1Backend},
+    derived::{GenericDerivedXPriv, GenericDerivedXPub},
+    keys::GenericPrivkey,
+    Bip32Error,
+};
+
+/// A 32-byte BIP340 x-only public key: the x-coordinate of a secp256k1 point, with the
+/// y-coordinate's parity implied to be even.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XOnlyPubkey(pub [u8; 32]);
+
+/// `tagged_hash(tag, m) = SHA256(SHA256(tag) || SHA256(tag) || m)`, as defined in BIP340.
+fn tagged_hash(tag: &str, msg: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.input(&tag_hash[..]);
+    hasher.input(&tag_hash[..]);
+    hasher.input(msg);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&hasher.result());
+    out
+}
+
+/// The BIP341 tweak scalar `t = tagged_hash("TapTweak", internal_key_x || merkle_root)`.
+fn tap_tweak_scalar(internal_key_x: &[u8; 32], merkle_root: &[u8]) -> [u8; 32] {
+    let mut msg = Vec::with_capacity(32 + merkle_root.len());
+    msg.extend_from_slice(internal_key_x);
+    msg.extend_from_slice(merkle_root);
+    tagged_hash("TapTweak", &msg)
+}
+
+/// `Q = P + t*G`, returning the resulting point's x-only pubkey. `P` is always taken as the
+/// even-y lift of `internal_key_x`, so this takes no parity argument; the odd-y case only
+/// matters for the private scalar, which `scalar_add_tweak` negates first.
+#[cfg(feature = "rust-secp")]
+fn point_add_tweak(internal_key_x: &[u8; 32], tweak: &[u8; 32]) -> Result<XOnlyPubkey, Bip32Error> {
+    use secp256k1::{PublicKey, Secp256k1};
+
+    let secp = Secp256k1::new();
+    // Reconstruct P as the even-y point with this x-coordinate (the canonical BIP340 lift_x).
+    let mut compressed = [0u8; 33];
+    compressed[0] = 0x02;
+    compressed[1..].copy_from_slice(internal_key_x);
+    let p = PublicKey::from_slice(&compressed).map_err(|_| Bip32Error::InvalidKey)?;
+
+    let mut tweaked = p;
+    tweaked.add_exp_assign(&secp, tweak).map_err(|_| Bip32Error::InvalidKey)?;
+
+    let serialized = tweaked.serialize();
+    let mut x_only = [0u8; 32];
+    x_only.copy_from_slice(&serialized[1..33]);
+    Ok(XOnlyPubkey(x_only))
+}
+
+#[cfg(feature = "libsecp")]
+fn point_add_tweak(internal_key_x: &[u8; 32], tweak: &[u8; 32]) -> Result<XOnlyPubkey, Bip32Error> {
+    use libsecp256k1::{PublicKey, SecretKey};
+
+    let mut compressed = [0u8; 33];
+    compressed[0] = 0x02;
+    compressed[1..].copy_from_slice(internal_key_x);
+    let mut p = PublicKey::parse_compressed(&compressed).map_err(|_| Bip32Error::InvalidKey)?;
+
+    let t = SecretKey::parse(tweak).map_err(|_| Bip32Error::InvalidKey)?;
+    p.tweak_add_assign(&t).map_err(|_| Bip32Error::InvalidKey)?;
+
+    let serialized = p.serialize_compressed();
+    let mut x_only = [0u8; 32];
+    x_only.copy_from_slice(&serialized[1..33]);
+    Ok(XOnlyPubkey(x_only))
+}
+
+/// `d' = (-d mod n)` if `internal_parity_odd`, else `d`; then `d'' = d' + t mod n`. This is the
+/// private-key half of `Q = P + t*G`: negate first so the scalar we hold always corresponds to
+/// the even-y lift of `P`, matching the x-only `internal_key_x` we tweaked against.
+#[cfg(feature = "rust-secp")]
+fn scalar_add_tweak(key: [u8; 32], internal_parity_odd: bool, tweak: &[u8; 32]) -> Result<[u8; 32], Bip32Error> {
+    use secp256k1::SecretKey;
+
+    let mut sk = SecretKey::from_slice(&key).map_err(|_| Bip32Error::InvalidKey)?;
+    if internal_parity_odd {
+        sk.negate_assign();
+    }
+    sk.add_assign(tweak).map_err(|_| Bip32Error::InvalidKey)?;
+    Ok(sk[..].try_into().expect("SecretKey is 32 bytes"))
+}
+
+#[cfg(feature = "libsecp")]
+fn scalar_add_tweak(key: [u8; 32], internal_parity_odd: bool, tweak: &[u8; 32]) -> Result<[u8; 32], Bip32Error> {
+    use libsecp256k1::SecretKey;
+
+    let mut sk = SecretKey::parse(&key).map_err(|_| Bip32Error::InvalidKey)?;
+    if internal_parity_odd {
+        sk = SecretKey::parse(&sk.neg().b32()).map_err(|_| Bip32Error::InvalidKey)?;
+    }
+    let t = SecretKey::parse(tweak).map_err(|_| Bip32Error::InvalidKey)?;
+    sk.tweak_add_assign(&t).map_err(|_| Bip32Error::InvalidKey)?;
+    Ok(sk.serialize())
+}
+
+impl<'a, T: Secp256k1Backend<'a>> GenericDerivedXPub<'a, T> {
+    /// The 32-byte x-only public key BIP340/BIP341 use in place of a full 33-byte SEC1 pubkey.
+    pub fn to_x_only(&self) -> Result<XOnlyPubkey, Bip32Error> {
+        let pubkey_bytes = self.derive_pubkey()?;
+        let mut x_only = [0u8; 32];
+        x_only.copy_from_slice(&pubkey_bytes[1..33]);
+        Ok(XOnlyPubkey(x_only))
+    }
+
+    /// Whether this key's untweaked point has an odd y-coordinate (the compressed SEC1 prefix
+    /// byte is `0x03`).
+    fn parity_odd(&self) -> Result<bool, Bip32Error> {
+        Ok(self.derive_pubkey()?[0] == 0x03)
+    }
+
+    /// The BIP341 Taproot output key for this internal key and an (optional) script tree merkle
+    /// root. Pass an empty slice for a BIP86 key-path-only output.
+    pub fn tap_tweak(&self, merkle_root: &[u8]) -> Result<XOnlyPubkey, Bip32Error> {
+        let internal = self.to_x_only()?;
+        let t = tap_tweak_scalar(&internal.0, merkle_root);
+        point_add_tweak(&internal.0, &t)
+    }
+
+    /// The BIP86 Taproot output key: a key-path-only tweak with an empty merkle root.
+    pub fn bip86_tap_tweak(&self) -> Result<XOnlyPubkey, Bip32Error> {
+        self.tap_tweak(&[])
+    }
+}
+
+impl<'a, T: Secp256k1Backend<'a>> GenericDerivedXPriv<'a, T>
+where
+    T::Privkey: ScalarDeserialize + ScalarSerialize,
+{
+    /// The BIP341 Taproot output key and the matching tweaked private key for this internal key
+    /// and an (optional) script tree merkle root. This is *not* a BIP32 node (it has no chain
+    /// code), so it is returned as a plain `GenericPrivkey` rather than a `GenericDerivedXPriv`:
+    /// nothing should derive further children from a Taproot output key.
+    pub fn tap_tweak(&self, merkle_root: &[u8]) -> Result<(XOnlyPubkey, GenericPrivkey<'a, T>), Bip32Error> {
+        let xpub = self.to_derived_xpub()?;
+        let internal = xpub.to_x_only()?;
+        let internal_parity_odd = xpub.parity_odd()?;
+        let t = tap_tweak_scalar(&internal.0, merkle_root);
+
+        let output_key = point_add_tweak(&internal.0, &t)?;
+        let tweaked_scalar = scalar_add_tweak(self.xpriv.privkey.key.privkey_array(), internal_parity_odd, &t)?;
+
+        let backend = self.backend()?;
+        let tweaked_key = T::Privkey::from_privkey_array(tweaked_scalar)?;
+        Ok((
+            output_key,
+            GenericPrivkey {
+                key: tweaked_key,
+                backend: Some(backend),
+            },
+        ))
+    }
+
+    /// The BIP86 Taproot output key and matching tweaked private key: a key-path-only tweak with
+    /// an empty merkle root, for users who only ever spend via the key path.
+    pub fn bip86_tap_tweak(&self) -> Result<(XOnlyPubkey, GenericPrivkey<'a, T>), Bip32Error> {
+        self.tap_tweak(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP86 test vector 1 (bitcoin/bips bip-0086.mediawiki): the account 0, receiving index 0
+    // key for the vector's seed. Internal key and the resulting (empty-merkle-root) Taproot
+    // output key, both x-only.
+    const INTERNAL_KEY: &str = "cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115";
+    const OUTPUT_KEY: &str = "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c";
+
+    fn from_hex(s: &str) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn it_computes_the_bip86_tap_tweak_output_key() {
+        let internal = from_hex(INTERNAL_KEY);
+        let t = tap_tweak_scalar(&internal, &[]);
+        let output = point_add_tweak(&internal, &t).unwrap();
+        assert_eq!(output.0, from_hex(OUTPUT_KEY));
+    }
+}
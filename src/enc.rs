@@ -1,9 +1,13 @@
 //! Specifies an abstract `AddressEncoder` that is used to convert `Address` to/from the in-memory
 //! representation of the associated spending constraint.
+//!
+//! `WitnessProgram` carries the bech32/bech32m logic needed for the Bitcoin encoder's
+//! `Address::WitnessProgram` variant (BIP350): v0 programs round-trip through bech32, v1+
+//! (including Taproot) through bech32m, and mixing the two is a decode error.
 
-use crate::{
-    types::tx::{RecipientIdentifier},
-};
+use sha2::{Digest, Sha256};
+
+use crate::types::{script::ScriptPubkey, tx::RecipientIdentifier};
 
 /// An AddressEncoder encodes and decodes addresses. This struct is used by the Builder to decode
 /// addresses, and is associated with a Network object. It handles converting addresses to
@@ -33,3 +37,422 @@ pub trait AddressEncoder {
     /// Convert a string into an address.
     fn wrap_string(s: String) -> Result<Self::Address, Self::Error>;
 }
+
+/// A segwit witness program: a witness version (0-16) and a 2-40 byte program, as carried by a
+/// `scriptPubKey` of the form `OP_n <program>`. [BIP350](https://github.com/bitcoin/bips/blob/master/bip-0350.mediawiki)
+/// requires version 0 programs to be encoded with bech32, and version 1+ programs (including
+/// Taproot) with bech32m.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WitnessProgram {
+    /// The witness version, `0..=16`.
+    pub version: u8,
+    /// The program itself, `2..=40` bytes.
+    pub program: Vec<u8>,
+}
+
+impl WitnessProgram {
+    /// Build a witness program, checking the length constraint from BIP141.
+    pub fn new(version: u8, program: Vec<u8>) -> Result<Self, WitnessProgramError> {
+        if program.len() < 2 || program.len() > 40 {
+            return Err(WitnessProgramError::BadProgramLength(program.len()));
+        }
+        Ok(Self { version, program })
+    }
+
+    /// Whether this program must be bech32m-encoded (BIP350): true for every version except 0.
+    pub fn needs_bech32m(&self) -> bool {
+        self.version != 0
+    }
+}
+
+/// Errors constructing or encoding a `WitnessProgram`.
+#[derive(Debug)]
+pub enum WitnessProgramError {
+    /// The program was shorter than 2 or longer than 40 bytes.
+    BadProgramLength(usize),
+    /// Bubbled up from the `bech32` crate.
+    Bech32(bech32::Error),
+    /// A v0 program was bech32m-encoded, or a v1+ program was bech32-encoded.
+    WrongVariant,
+}
+
+impl From<bech32::Error> for WitnessProgramError {
+    fn from(e: bech32::Error) -> Self {
+        WitnessProgramError::Bech32(e)
+    }
+}
+
+impl WitnessProgram {
+    /// Encode this witness program as a bech32 (v0) or bech32m (v1+) string with the given human
+    /// readable prefix (e.g. `"bc"` or `"tb"`).
+    pub fn encode(&self, hrp: &str) -> Result<String, WitnessProgramError> {
+        use bech32::ToBase32;
+
+        let variant = if self.needs_bech32m() {
+            bech32::Variant::Bech32m
+        } else {
+            bech32::Variant::Bech32
+        };
+
+        let mut data = vec![bech32::u5::try_from_u8(self.version)?];
+        data.extend(self.program.to_base32());
+        Ok(bech32::encode(hrp, data, variant)?)
+    }
+
+    /// Decode a bech32/bech32m string into a witness program, enforcing that v0 programs use
+    /// bech32 and v1+ programs use bech32m.
+    pub fn decode(s: &str) -> Result<(String, Self), WitnessProgramError> {
+        use bech32::FromBase32;
+
+        let (hrp, data, variant) = bech32::decode(s)?;
+        let (version_u5, program_u5) = data.split_first().ok_or(WitnessProgramError::BadProgramLength(0))?;
+        let version = version_u5.to_u8();
+        let program = Vec::<u8>::from_base32(program_u5)?;
+
+        let program = Self::new(version, program)?;
+        let expected = if program.needs_bech32m() {
+            bech32::Variant::Bech32m
+        } else {
+            bech32::Variant::Bech32
+        };
+        if variant != expected {
+            return Err(WitnessProgramError::WrongVariant);
+        }
+
+        Ok((hrp, program))
+    }
+}
+
+/// A user-facing Bitcoin address: legacy base58check P2PKH/P2SH, or a bech32 (v0) / bech32m
+/// (v1+) encoded witness program (BIP350), covering native segwit and Taproot alike.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    /// A base58check-encoded P2PKH address (`1...`/`m...`/`n...`).
+    Pkh(String),
+    /// A base58check-encoded P2SH address (`3...`/`2...`).
+    Sh(String),
+    /// A bech32/bech32m-encoded witness program address (`bc1.../tb1...`).
+    WitnessProgram(String),
+}
+
+/// Errors encoding or decoding a Bitcoin `Address`.
+#[derive(Debug)]
+pub enum EncodingError {
+    /// Bubbled up from `WitnessProgram` encode/decode.
+    WitnessProgram(WitnessProgramError),
+    /// The scriptPubkey didn't match any address type this encoder knows how to produce.
+    UnknownScriptType,
+    /// The address string wasn't valid base58, didn't checksum-verify, or carried a version byte
+    /// this network doesn't use.
+    BadBase58Address,
+    /// The address string decoded as a valid witness program, but for a different network's HRP
+    /// (e.g. a `tb1...` testnet address passed to a mainnet encoder).
+    WrongNetworkHrp,
+}
+
+impl From<WitnessProgramError> for EncodingError {
+    fn from(e: WitnessProgramError) -> Self {
+        EncodingError::WitnessProgram(e)
+    }
+}
+
+/// The network-specific constants a Bitcoin `AddressEncoder` needs: the base58check version
+/// bytes for P2PKH/P2SH, and the bech32/bech32m human-readable prefix for witness programs.
+pub trait BitcoinNetwork {
+    /// The base58check version byte for a P2PKH address.
+    const PKH_VERSION: u8;
+    /// The base58check version byte for a P2SH address.
+    const SH_VERSION: u8;
+    /// The bech32/bech32m human-readable prefix for a witness program address.
+    const HRP: &'static str;
+}
+
+/// Mainnet address constants: `1.../3.../bc1...`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Main;
+
+impl BitcoinNetwork for Main {
+    const PKH_VERSION: u8 = 0x00;
+    const SH_VERSION: u8 = 0x05;
+    const HRP: &'static str = "bc";
+}
+
+/// Testnet (and regtest/signet, which reuse the same prefixes) address constants:
+/// `m.../n.../2.../tb1...`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Test;
+
+impl BitcoinNetwork for Test {
+    const PKH_VERSION: u8 = 0x6f;
+    const SH_VERSION: u8 = 0xc4;
+    const HRP: &'static str = "tb";
+}
+
+/// A Bitcoin `AddressEncoder`, parameterized over network-specific version bytes and HRP.
+pub struct BitcoinEncoder<N: BitcoinNetwork>(std::marker::PhantomData<N>);
+
+/// The mainnet Bitcoin address encoder.
+pub type MainnetEncoder = BitcoinEncoder<Main>;
+/// The testnet Bitcoin address encoder.
+pub type TestnetEncoder = BitcoinEncoder<Test>;
+
+impl<N: BitcoinNetwork> AddressEncoder for BitcoinEncoder<N> {
+    type Address = Address;
+    type Error = EncodingError;
+    type RecipientIdentifier = ScriptPubkey;
+
+    fn encode_address(s: ScriptPubkey) -> Result<Address, EncodingError> {
+        let script: Vec<u8> = s.into();
+
+        if let Some(program) = witness_program_from_script(&script) {
+            return Ok(Address::WitnessProgram(program.encode(N::HRP)?));
+        }
+
+        // P2PKH: OP_DUP OP_HASH160 <20> <hash> OP_EQUALVERIFY OP_CHECKSIG
+        if script.len() == 25
+            && script[0] == 0x76
+            && script[1] == 0xa9
+            && script[2] == 0x14
+            && script[23] == 0x88
+            && script[24] == 0xac
+        {
+            return Ok(Address::Pkh(base58check_encode(N::PKH_VERSION, &script[3..23])));
+        }
+
+        // P2SH: OP_HASH160 <20> <hash> OP_EQUAL
+        if script.len() == 23 && script[0] == 0xa9 && script[1] == 0x14 && script[22] == 0x87 {
+            return Ok(Address::Sh(base58check_encode(N::SH_VERSION, &script[2..22])));
+        }
+
+        Err(EncodingError::UnknownScriptType)
+    }
+
+    fn decode_address(addr: Address) -> Result<ScriptPubkey, EncodingError> {
+        let script = match addr {
+            Address::WitnessProgram(s) => {
+                let (_hrp, program) = WitnessProgram::decode(&s)?;
+                witness_script_from_program(&program)
+            }
+            Address::Pkh(s) => {
+                let hash = base58check_decode(N::PKH_VERSION, &s)?;
+                let mut script = vec![0x76, 0xa9, 0x14];
+                script.extend_from_slice(&hash);
+                script.extend_from_slice(&[0x88, 0xac]);
+                script
+            }
+            Address::Sh(s) => {
+                let hash = base58check_decode(N::SH_VERSION, &s)?;
+                let mut script = vec![0xa9, 0x14];
+                script.extend_from_slice(&hash);
+                script.push(0x87);
+                script
+            }
+        };
+        Ok(script.into())
+    }
+
+    fn wrap_string(s: String) -> Result<Address, EncodingError> {
+        if let Ok((hrp, _program)) = WitnessProgram::decode(&s) {
+            if hrp == N::HRP {
+                return Ok(Address::WitnessProgram(s));
+            }
+            return Err(EncodingError::WrongNetworkHrp);
+        }
+        match base58check_version(&s) {
+            Some(version) if version == N::PKH_VERSION => Ok(Address::Pkh(s)),
+            Some(version) if version == N::SH_VERSION => Ok(Address::Sh(s)),
+            _ => Err(EncodingError::BadBase58Address),
+        }
+    }
+}
+
+/// A `scriptPubkey`'s witness program, if it's of the form `OP_n <2-40 byte program>` for
+/// `n` in `0..=16` (`OP_0` through `OP_16`).
+fn witness_program_from_script(script: &[u8]) -> Option<WitnessProgram> {
+    if script.len() < 4 || script.len() > 42 {
+        return None;
+    }
+    let version = match script[0] {
+        0x00 => 0,
+        op @ 0x51..=0x60 => op - 0x50,
+        _ => return None,
+    };
+    if script[1] as usize != script.len() - 2 {
+        return None;
+    }
+    WitnessProgram::new(version, script[2..].to_vec()).ok()
+}
+
+/// The `scriptPubkey` bytes (`OP_n <program>`) a witness program is carried in.
+fn witness_script_from_program(program: &WitnessProgram) -> Vec<u8> {
+    let mut script = Vec::with_capacity(2 + program.program.len());
+    script.push(if program.version == 0 { 0x00 } else { 0x50 + program.version });
+    script.push(program.program.len() as u8);
+    script.extend_from_slice(&program.program);
+    script
+}
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+fn double_sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    let first = hasher.result();
+    let second = Sha256::digest(&first);
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&second);
+    digest
+}
+
+/// Base58check-encode `version || payload`, appending the leading 4 bytes of
+/// `doubleSha256(version || payload)` as a checksum.
+fn base58check_encode(version: u8, payload: &[u8]) -> String {
+    let mut data = Vec::with_capacity(1 + payload.len() + 4);
+    data.push(version);
+    data.extend_from_slice(payload);
+    let checksum = double_sha256(&data);
+    data.extend_from_slice(&checksum[..4]);
+
+    let zeros = data.iter().take_while(|&&b| b == 0).count();
+    let mut digits: Vec<u8> = vec![0];
+    for &byte in &data {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let mut out: Vec<u8> = std::iter::repeat(BASE58_ALPHABET[0]).take(zeros).collect();
+    out.extend(digits.iter().rev().map(|&d| BASE58_ALPHABET[d as usize]));
+    String::from_utf8(out).expect("base58 alphabet is ASCII")
+}
+
+/// Base58check-decode `s`, verifying its checksum and that its version byte is `expected_version`,
+/// and returning the payload between them.
+fn base58check_decode(expected_version: u8, s: &str) -> Result<Vec<u8>, EncodingError> {
+    let data = base58_decode(s).ok_or(EncodingError::BadBase58Address)?;
+    if data.len() < 5 {
+        return Err(EncodingError::BadBase58Address);
+    }
+    let (payload_with_version, checksum) = data.split_at(data.len() - 4);
+    if double_sha256(payload_with_version)[..4] != *checksum {
+        return Err(EncodingError::BadBase58Address);
+    }
+    if payload_with_version[0] != expected_version {
+        return Err(EncodingError::BadBase58Address);
+    }
+    Ok(payload_with_version[1..].to_vec())
+}
+
+/// The base58check version byte of `s`, if it decodes and checksum-verifies.
+fn base58check_version(s: &str) -> Option<u8> {
+    let data = base58_decode(s)?;
+    if data.len() < 5 {
+        return None;
+    }
+    let (payload_with_version, checksum) = data.split_at(data.len() - 4);
+    if double_sha256(payload_with_version)[..4] != *checksum {
+        return None;
+    }
+    Some(payload_with_version[0])
+}
+
+fn base58_decode(s: &str) -> Option<Vec<u8>> {
+    let zeros = s.bytes().take_while(|&b| b == BASE58_ALPHABET[0]).count();
+    let mut bytes: Vec<u8> = vec![0];
+    for c in s.bytes() {
+        let mut value = BASE58_ALPHABET.iter().position(|&a| a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            value += (*byte as u32) * 58;
+            *byte = (value & 0xff) as u8;
+            value >>= 8;
+        }
+        while value > 0 {
+            bytes.push((value & 0xff) as u8);
+            value >>= 8;
+        }
+    }
+    let mut out: Vec<u8> = std::iter::repeat(0).take(zeros).collect();
+    out.extend(bytes.iter().rev());
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP350 Taproot test vector: `bc1p...` address / x-only witness program.
+    const TAPROOT_ADDRESS: &str = "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0";
+    const TAPROOT_PROGRAM: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn hex_bytes(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_encodes_a_v1_witness_program_as_a_bip350_bech32m_address() {
+        let program = WitnessProgram::new(1, hex_bytes(TAPROOT_PROGRAM)).unwrap();
+        assert_eq!(program.encode("bc").unwrap(), TAPROOT_ADDRESS);
+    }
+
+    #[test]
+    fn it_round_trips_a_taproot_scriptpubkey_through_the_mainnet_encoder() {
+        let mut script = vec![0x51, 0x20];
+        script.extend_from_slice(&hex_bytes(TAPROOT_PROGRAM));
+        let spk = ScriptPubkey::from(script.clone());
+
+        let addr = MainnetEncoder::encode_address(spk).unwrap();
+        assert_eq!(addr, Address::WitnessProgram(TAPROOT_ADDRESS.to_string()));
+
+        let decoded: Vec<u8> = MainnetEncoder::decode_address(addr).unwrap().into();
+        assert_eq!(decoded, script);
+    }
+
+    #[test]
+    fn it_round_trips_a_p2pkh_scriptpubkey_through_the_mainnet_encoder() {
+        let hash = [0x11u8; 20];
+        let mut script = vec![0x76, 0xa9, 0x14];
+        script.extend_from_slice(&hash);
+        script.extend_from_slice(&[0x88, 0xac]);
+        let spk = ScriptPubkey::from(script.clone());
+
+        let addr = MainnetEncoder::encode_address(spk).unwrap();
+        let decoded: Vec<u8> = MainnetEncoder::decode_address(addr).unwrap().into();
+        assert_eq!(decoded, script);
+    }
+
+    #[test]
+    fn it_rejects_a_witness_program_string_for_the_wrong_network() {
+        let testnet_address = WitnessProgram::new(1, hex_bytes(TAPROOT_PROGRAM))
+            .unwrap()
+            .encode("tb")
+            .unwrap();
+
+        assert!(matches!(
+            MainnetEncoder::wrap_string(testnet_address.clone()),
+            Err(EncodingError::WrongNetworkHrp)
+        ));
+        assert_eq!(
+            TestnetEncoder::wrap_string(testnet_address.clone()).unwrap(),
+            Address::WitnessProgram(testnet_address)
+        );
+    }
+
+    #[test]
+    fn it_rejects_an_unknown_scriptpubkey() {
+        let spk = ScriptPubkey::from(vec![0x6a, 0x00]); // OP_RETURN
+        assert!(matches!(
+            MainnetEncoder::encode_address(spk),
+            Err(EncodingError::UnknownScriptType)
+        ));
+    }
+}
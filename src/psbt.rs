@@ -0,0 +1,85 @@
+//! Partially Signed Bitcoin Transaction (PSBT) primitives, per BIP174.
+//!
+//! The model here is deliberately narrow: just enough structure to walk an unsigned
+//! transaction's inputs and carry the key-value pairs a signer needs, without implementing the
+//! full PSBT serialization format.
+
+use std::collections::BTreeMap;
+
+use bip32::path::KeyDerivation;
+
+pub mod signer;
+
+/// The BIP32 derivation info a PSBT input records for one of the keys that might be able to
+/// sign it, straight out of a `PSBT_IN_BIP32_DERIVATION` key-value pair.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bip32Derivation {
+    /// The serialized public key this derivation info describes.
+    pub pubkey: Vec<u8>,
+    /// The derivation that produced `pubkey`, rooted at a master key fingerprint.
+    pub derivation: KeyDerivation,
+}
+
+/// One input of a `Psbt`.
+#[derive(Clone, Debug, Default)]
+pub struct PsbtInput {
+    /// The value, in satoshis, and `scriptPubKey` of the output this input spends. Present for
+    /// inputs that spend a witness program.
+    pub witness_utxo: Option<(u64, Vec<u8>)>,
+    /// The BIP32 derivation info for every key that might be able to sign this input.
+    pub bip32_derivation: Vec<Bip32Derivation>,
+    /// Signatures already collected for this input, keyed by the signing pubkey.
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// The `redeemScript`, for inputs nested in P2SH (including P2SH-wrapped P2WPKH/P2WSH).
+    pub redeem_script: Option<Vec<u8>>,
+    /// The `witnessScript`, for inputs spending P2WSH.
+    pub witness_script: Option<Vec<u8>>,
+    /// The raw `PSBT_IN_SIGHASH_TYPE` value (`SIGHASH_ALL` if absent, per BIP174).
+    pub sighash_type: Option<u32>,
+}
+
+/// A partially-signed Bitcoin transaction. Carries the unsigned transaction alongside the
+/// per-input metadata needed to sign and finalize it.
+#[derive(Clone, Debug, Default)]
+pub struct Psbt {
+    /// The raw serialized unsigned transaction (`PSBT_GLOBAL_UNSIGNED_TX`).
+    pub unsigned_tx: Vec<u8>,
+    /// The transaction's 4-byte LE `nVersion`.
+    pub version: [u8; 4],
+    /// The transaction's 4-byte LE `nLocktime`.
+    pub locktime: [u8; 4],
+    /// The serialized `outpoint` of every input, in the transaction's input order.
+    pub prevouts: Vec<[u8; 36]>,
+    /// The 4-byte LE `nSequence` of every input, in the transaction's input order.
+    pub sequences: Vec<[u8; 4]>,
+    /// Every output, pre-serialized as `amount(8-byte LE) || scriptPubKey` (length-prefixed).
+    pub outputs: Vec<Vec<u8>>,
+    /// Per-input metadata, in the same order as `unsigned_tx`'s inputs.
+    pub inputs: Vec<PsbtInput>,
+}
+
+impl Psbt {
+    /// Assemble the `Bip143Args` needed to compute the signature digest for input `index`,
+    /// spending a script whose `scriptCode` is `script_code`.
+    pub fn bip143_args(
+        &self,
+        index: usize,
+        script_code: &[u8],
+        amount: u64,
+        sighash_flag: crate::sighash::SighashFlag,
+        anyonecanpay: bool,
+    ) -> crate::sighash::Bip143Args {
+        crate::sighash::Bip143Args {
+            prevouts: &self.prevouts,
+            sequences: &self.sequences,
+            outputs: &self.outputs,
+            index,
+            script_code,
+            amount,
+            version: self.version,
+            locktime: self.locktime,
+            sighash_flag,
+            anyonecanpay,
+        }
+    }
+}
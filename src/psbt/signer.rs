@@ -0,0 +1,247 @@
+//! A `Signer` role that fills in `partial_sigs` on a `Psbt` using a `DerivedXPriv`.
+
+use bip32::{
+    curve::model::{PointDeserialize, Secp256k1Backend},
+    derived::{GenericDerivedPubkey, GenericDerivedXPriv},
+    keys::GenericPubkey,
+    model::{DerivedKey, SigningKey},
+    Bip32Error, DerivedXPriv,
+};
+
+use crate::{
+    psbt::{Psbt, PsbtInput},
+    sighash::{bip143_sighash, SighashFlag},
+};
+
+/// Errors arising while signing a `Psbt`.
+#[derive(Debug)]
+pub enum SignerError {
+    /// Bubbled up from key derivation or signing.
+    Bip32(Bip32Error),
+    /// The input's `redeemScript`/`witnessScript`/`witness_utxo` don't describe a scriptCode
+    /// this signer knows how to build (only P2WPKH, P2SH-P2WPKH, and P2WSH are supported).
+    UnsupportedScript,
+    /// The input's `PSBT_IN_SIGHASH_TYPE` wasn't one of the ALL/NONE/SINGLE (± ANYONECANPAY)
+    /// values BIP143 defines.
+    UnsupportedSighashType(u32),
+}
+
+impl From<Bip32Error> for SignerError {
+    fn from(e: Bip32Error) -> Self {
+        SignerError::Bip32(e)
+    }
+}
+
+/// A role that can contribute signatures to a `Psbt`.
+pub trait Signer {
+    /// Sign every input this key is able to sign, returning the number of signatures added.
+    fn sign_psbt(&self, psbt: &mut Psbt) -> Result<usize, SignerError>;
+}
+
+impl<'a> Signer for DerivedXPriv<'a> {
+    fn sign_psbt(&self, psbt: &mut Psbt) -> Result<usize, SignerError> {
+        let mut signed = 0;
+        for index in 0..psbt.inputs.len() {
+            if sign_input(self, psbt, index)? {
+                signed += 1;
+            }
+        }
+        Ok(signed)
+    }
+}
+
+/// Try to sign a single input with `xpriv`, returning whether a signature was added.
+///
+/// Walks the input's recorded BIP32 derivations, skipping any whose master fingerprint doesn't
+/// match `xpriv`'s own, then uses `is_private_ancestor_of` to confirm the recorded pubkey really
+/// is reachable from `xpriv` before deriving the signing child, computing the BIP143 digest, and
+/// committing a partial sig.
+fn sign_input<'a>(
+    xpriv: &GenericDerivedXPriv<'a, bip32::Secp256k1<'a>>,
+    psbt: &mut Psbt,
+    index: usize,
+) -> Result<bool, SignerError> {
+    let amount = match psbt.inputs[index].witness_utxo.as_ref() {
+        Some((amount, _)) => *amount,
+        None => return Ok(false),
+    };
+    let script_code = script_code(&psbt.inputs[index])?;
+    let (sighash_flag, anyonecanpay) =
+        decode_sighash_type(psbt.inputs[index].sighash_type.unwrap_or(0x01))?;
+
+    for entry in psbt.inputs[index].bip32_derivation.clone() {
+        if entry.derivation.root != xpriv.derivation.root {
+            continue;
+        }
+
+        let target = derived_pubkey_from_bytes(xpriv, &entry.pubkey, entry.derivation.clone())?;
+        if !xpriv.is_private_ancestor_of(&target)? {
+            continue;
+        }
+
+        // Derive via the path relative to `xpriv`, not `entry.derivation.path` (the full path
+        // from the master): `xpriv` itself may already be a non-root key (e.g. an account xpriv),
+        // in which case re-deriving the full path from it would walk past `target` entirely.
+        let path = match xpriv.path_to_descendant(&target) {
+            Some(path) => path,
+            None => continue,
+        };
+        let child = xpriv.derive_private_path(&path)?;
+
+        let args = psbt.bip143_args(index, &script_code, amount, sighash_flag, anyonecanpay);
+        let sighash = bip143_sighash(&args);
+        let signature = child.backend()?.sign_digest(child.privkey(), sighash)?;
+
+        let mut sig = signature.serialize_der();
+        sig.push(sighash_flag.to_u32(anyonecanpay) as u8);
+        psbt.inputs[index].partial_sigs.insert(entry.pubkey, sig);
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// The BIP143 `scriptCode` for `input`: for P2WSH (a `witness_script` is present) it's the
+/// witness script itself; for P2WPKH and P2SH-wrapped P2WPKH it's the implied
+/// `OP_DUP OP_HASH160 <20> OP_EQUALVERIFY OP_CHECKSIG` built from the pubkey hash carried in the
+/// witness program. Either way the result is length-prefixed, as it appears in the digest
+/// preimage.
+fn script_code(input: &PsbtInput) -> Result<Vec<u8>, SignerError> {
+    if let Some(witness_script) = &input.witness_script {
+        return Ok(compact_size_prefixed(witness_script));
+    }
+
+    let program = match &input.redeem_script {
+        Some(redeem_script) => redeem_script,
+        None => match &input.witness_utxo {
+            Some((_, script_pubkey)) => script_pubkey,
+            None => return Err(SignerError::UnsupportedScript),
+        },
+    };
+
+    // A v0 P2WPKH witness program: OP_0 <20-byte pubkey hash>.
+    if program.len() != 22 || program[0] != 0x00 || program[1] != 0x14 {
+        return Err(SignerError::UnsupportedScript);
+    }
+    let pubkey_hash = &program[2..22];
+
+    let mut script = Vec::with_capacity(25);
+    script.push(0x76); // OP_DUP
+    script.push(0xa9); // OP_HASH160
+    script.push(0x14); // push 20 bytes
+    script.extend_from_slice(pubkey_hash);
+    script.push(0x88); // OP_EQUALVERIFY
+    script.push(0xac); // OP_CHECKSIG
+    Ok(compact_size_prefixed(&script))
+}
+
+/// Prefix `script` with its BIP143 CompactSize length, as it's written into the digest preimage.
+fn compact_size_prefixed(script: &[u8]) -> Vec<u8> {
+    let mut out = compact_size(script.len());
+    out.extend_from_slice(script);
+    out
+}
+
+fn compact_size(n: usize) -> Vec<u8> {
+    if n < 0xfd {
+        vec![n as u8]
+    } else if n <= 0xffff {
+        let mut out = vec![0xfd];
+        out.extend_from_slice(&(n as u16).to_le_bytes());
+        out
+    } else {
+        let mut out = vec![0xfe];
+        out.extend_from_slice(&(n as u32).to_le_bytes());
+        out
+    }
+}
+
+/// Split a raw `PSBT_IN_SIGHASH_TYPE` value into its `SighashFlag` and `SIGHASH_ANYONECANPAY`
+/// bit.
+fn decode_sighash_type(raw: u32) -> Result<(SighashFlag, bool), SignerError> {
+    let anyonecanpay = raw & 0x80 != 0;
+    let flag = match raw & !0x80 {
+        0x01 => SighashFlag::All,
+        0x02 => SighashFlag::None,
+        0x03 => SighashFlag::Single,
+        _ => return Err(SignerError::UnsupportedSighashType(raw)),
+    };
+    Ok((flag, anyonecanpay))
+}
+
+fn derived_pubkey_from_bytes<'a>(
+    xpriv: &GenericDerivedXPriv<'a, bip32::Secp256k1<'a>>,
+    pubkey_bytes: &[u8],
+    derivation: bip32::path::KeyDerivation,
+) -> Result<GenericDerivedPubkey<'a, bip32::Secp256k1<'a>>, Bip32Error> {
+    if pubkey_bytes.len() != 33 {
+        return Err(Bip32Error::InvalidKey);
+    }
+    let mut arr = [0u8; 33];
+    arr.copy_from_slice(pubkey_bytes);
+
+    let backend = xpriv.backend()?;
+    let key = <bip32::Secp256k1<'a> as Secp256k1Backend>::Pubkey::from_pubkey_array(arr)?;
+    Ok(GenericDerivedPubkey {
+        pubkey: GenericPubkey {
+            key,
+            backend: Some(backend),
+        },
+        derivation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_bytes(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_builds_the_implied_p2wpkh_script_code() {
+        let input = PsbtInput {
+            witness_utxo: Some((
+                100_000,
+                hex_bytes("0014841b80d2cc75f5345c482af96294d04fdd66b2b7"),
+            )),
+            ..Default::default()
+        };
+        assert_eq!(
+            script_code(&input).unwrap(),
+            hex_bytes("1976a914841b80d2cc75f5345c482af96294d04fdd66b2b788ac")
+        );
+    }
+
+    #[test]
+    fn it_prefers_witness_script_for_p2wsh() {
+        let witness_script = hex_bytes("51"); // a trivial 1-byte "script"
+        let input = PsbtInput {
+            witness_utxo: Some((100_000, hex_bytes("00201111111111111111111111111111111111111111111111111111111111111111"))),
+            witness_script: Some(witness_script.clone()),
+            ..Default::default()
+        };
+        assert_eq!(script_code(&input).unwrap(), compact_size_prefixed(&witness_script));
+    }
+
+    #[test]
+    fn it_rejects_an_unrecognized_program() {
+        let input = PsbtInput {
+            witness_utxo: Some((100_000, hex_bytes("76a914841b80d2cc75f5345c482af96294d04fdd66b2b788ac"))),
+            ..Default::default()
+        };
+        assert!(matches!(script_code(&input), Err(SignerError::UnsupportedScript)));
+    }
+
+    #[test]
+    fn it_decodes_sighash_types() {
+        assert_eq!(decode_sighash_type(0x01).unwrap(), (SighashFlag::All, false));
+        assert_eq!(decode_sighash_type(0x82).unwrap(), (SighashFlag::None, true));
+        assert_eq!(decode_sighash_type(0x03).unwrap(), (SighashFlag::Single, false));
+        assert!(matches!(decode_sighash_type(0x04), Err(SignerError::UnsupportedSighashType(0x04))));
+    }
+}
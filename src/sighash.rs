@@ -0,0 +1,238 @@
+//! BIP143 segwit signature hash computation.
+//!
+//! [BIP143](https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki) replaces the legacy
+//! signature hash algorithm for inputs that spend a witness program. Rather than re-serializing
+//! and re-hashing the whole transaction for every input, it precomputes three double-SHA256
+//! digests once per transaction (`hashPrevouts`, `hashSequence`, `hashOutputs`) and reuses them
+//! for every input signed.
+
+use std::io::Write;
+
+use sha2::{Digest, Sha256};
+
+use crate::{new_types::hashes::MarkedHash256, utils::Hash256Writer};
+
+/// The sighash flag a BIP143 digest is computed for, mirroring the legacy `SIGHASH_*` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SighashFlag {
+    /// Sign all inputs and all outputs.
+    All,
+    /// Sign all inputs, and no outputs.
+    None,
+    /// Sign all inputs, and only the output at the same index as the input being signed.
+    Single,
+}
+
+impl SighashFlag {
+    /// The raw sighash type, as written into the digest preimage and appended to the final DER
+    /// signature.
+    pub(crate) fn to_u32(self, anyonecanpay: bool) -> u32 {
+        let base = match self {
+            SighashFlag::All => 0x01,
+            SighashFlag::None => 0x02,
+            SighashFlag::Single => 0x03,
+        };
+        if anyonecanpay {
+            base | 0x80
+        } else {
+            base
+        }
+    }
+}
+
+/// The inputs to a single BIP143 digest computation. Deliberately decoupled from any concrete
+/// transaction type: callers pass in their own pre-serialized fields.
+pub struct Bip143Args<'a> {
+    /// The serialized `outpoint` (32-byte txid + 4-byte LE index) of every input, in the
+    /// transaction's input order.
+    pub prevouts: &'a [[u8; 36]],
+    /// The 4-byte LE `nSequence` of every input, in the transaction's input order.
+    pub sequences: &'a [[u8; 4]],
+    /// Every output, pre-serialized as `amount(8-byte LE) || scriptPubKey` (length-prefixed).
+    pub outputs: &'a [Vec<u8>],
+    /// The index, within `prevouts`/`sequences`, of the input being signed.
+    pub index: usize,
+    /// The `scriptCode` for the input being signed: for P2WPKH, the implied
+    /// `OP_DUP OP_HASH160 <hash> OP_EQUALVERIFY OP_CHECKSIG`; for P2WSH, the witness script.
+    pub script_code: &'a [u8],
+    /// The value, in satoshis, of the output the input being signed spends.
+    pub amount: u64,
+    /// The 4-byte LE `nVersion` of the transaction.
+    pub version: [u8; 4],
+    /// The 4-byte LE `nLocktime` of the transaction.
+    pub locktime: [u8; 4],
+    /// Which sighash flag to sign with.
+    pub sighash_flag: SighashFlag,
+    /// Whether `SIGHASH_ANYONECANPAY` is set.
+    pub anyonecanpay: bool,
+}
+
+/// Compute the BIP143 signature digest for a single input.
+pub fn bip143_sighash<T: MarkedHash256>(args: &Bip143Args) -> T {
+    let hash_prevouts = hash_prevouts(args);
+    let hash_sequence = hash_sequence(args);
+    let hash_outputs = hash_outputs(args);
+
+    let mut w = Hash256Writer::default();
+    w.write_all(&args.version).expect("Hash256Writer never fails");
+    w.write_all(&hash_prevouts[..]).expect("Hash256Writer never fails");
+    w.write_all(&hash_sequence[..]).expect("Hash256Writer never fails");
+    w.write_all(&args.prevouts[args.index]).expect("Hash256Writer never fails");
+    w.write_all(args.script_code).expect("Hash256Writer never fails");
+    w.write_all(&args.amount.to_le_bytes()).expect("Hash256Writer never fails");
+    w.write_all(&args.sequences[args.index]).expect("Hash256Writer never fails");
+    w.write_all(&hash_outputs[..]).expect("Hash256Writer never fails");
+    w.write_all(&args.locktime).expect("Hash256Writer never fails");
+    w.write_all(&args.sighash_flag.to_u32(args.anyonecanpay).to_le_bytes())
+        .expect("Hash256Writer never fails");
+    w.finish()
+}
+
+/// Double-SHA256 of the concatenation of `chunks`.
+fn double_sha256(chunks: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for chunk in chunks {
+        hasher.input(chunk);
+    }
+    let first = hasher.result();
+    let second = Sha256::digest(&first);
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&second);
+    digest
+}
+
+/// `hashPrevouts`: zeroed out under `SIGHASH_ANYONECANPAY`.
+fn hash_prevouts(args: &Bip143Args) -> [u8; 32] {
+    if args.anyonecanpay {
+        return [0u8; 32];
+    }
+    let refs: Vec<&[u8]> = args.prevouts.iter().map(|p| &p[..]).collect();
+    double_sha256(&refs)
+}
+
+/// `hashSequence`: zeroed out under `SIGHASH_ANYONECANPAY`, `SIGHASH_SINGLE`, or `SIGHASH_NONE`.
+fn hash_sequence(args: &Bip143Args) -> [u8; 32] {
+    if args.anyonecanpay || args.sighash_flag != SighashFlag::All {
+        return [0u8; 32];
+    }
+    let refs: Vec<&[u8]> = args.sequences.iter().map(|s| &s[..]).collect();
+    double_sha256(&refs)
+}
+
+/// `hashOutputs`: all outputs under `SIGHASH_ALL`, only the matching output under
+/// `SIGHASH_SINGLE` (zeroed if there is none), and zeroed under `SIGHASH_NONE`.
+fn hash_outputs(args: &Bip143Args) -> [u8; 32] {
+    match args.sighash_flag {
+        SighashFlag::All => {
+            let refs: Vec<&[u8]> = args.outputs.iter().map(|o| &o[..]).collect();
+            double_sha256(&refs)
+        }
+        SighashFlag::Single if args.index < args.outputs.len() => {
+            double_sha256(&[&args.outputs[args.index]])
+        }
+        _ => [0u8; 32],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex32(s: &str) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        out
+    }
+
+    fn hex_bytes(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // BIP143 "Native P2WPKH" example: a 2-input, 2-output transaction where input 1 (the one
+    // being signed below) spends a P2WPKH output.
+    fn example_tx() -> ([[u8; 36]; 2], [[u8; 4]; 2], Vec<Vec<u8>>) {
+        let mut prevout0 = [0u8; 36];
+        prevout0[..32].copy_from_slice(&from_hex32(
+            "fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f",
+        ));
+
+        let mut prevout1 = [0u8; 36];
+        prevout1[..32].copy_from_slice(&from_hex32(
+            "ef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a",
+        ));
+        prevout1[32..].copy_from_slice(&1u32.to_le_bytes());
+
+        let outputs = vec![
+            hex_bytes("202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac"),
+            hex_bytes("9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac"),
+        ];
+
+        (
+            [prevout0, prevout1],
+            [0xffff_ffeeu32.to_le_bytes(), 0xffff_ffffu32.to_le_bytes()],
+            outputs,
+        )
+    }
+
+    #[test]
+    fn it_matches_the_bip143_spec_example_component_hashes() {
+        let (prevouts, sequences, outputs) = example_tx();
+        let args = Bip143Args {
+            prevouts: &prevouts,
+            sequences: &sequences,
+            outputs: &outputs,
+            index: 1,
+            script_code: &[],
+            amount: 0,
+            version: 1u32.to_le_bytes(),
+            locktime: 17u32.to_le_bytes(),
+            sighash_flag: SighashFlag::All,
+            anyonecanpay: false,
+        };
+
+        assert_eq!(
+            hash_prevouts(&args),
+            from_hex32("96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37")
+        );
+        assert_eq!(
+            hash_sequence(&args),
+            from_hex32("52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b")
+        );
+        assert_eq!(
+            hash_outputs(&args),
+            from_hex32("863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5")
+        );
+    }
+
+    #[test]
+    fn it_zeroes_precomputed_hashes_per_sighash_flag() {
+        let (prevouts, sequences, outputs) = example_tx();
+        let mut args = Bip143Args {
+            prevouts: &prevouts,
+            sequences: &sequences,
+            outputs: &outputs,
+            index: 1,
+            script_code: &[],
+            amount: 0,
+            version: [0; 4],
+            locktime: [0; 4],
+            sighash_flag: SighashFlag::All,
+            anyonecanpay: true,
+        };
+        assert_eq!(hash_prevouts(&args), [0u8; 32]);
+        assert_eq!(hash_sequence(&args), [0u8; 32]);
+
+        args.anyonecanpay = false;
+        args.sighash_flag = SighashFlag::None;
+        assert_eq!(hash_sequence(&args), [0u8; 32]);
+        assert_eq!(hash_outputs(&args), [0u8; 32]);
+
+        args.sighash_flag = SighashFlag::Single;
+        assert_eq!(hash_outputs(&args), double_sha256(&[&outputs[1]]));
+    }
+}
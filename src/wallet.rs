@@ -0,0 +1,163 @@
+//! BIP44-style account-level address discovery.
+//!
+//! Given an account xpub, lazily derives the external (`0/i`) and internal (`1/i`) chains and
+//! encodes each child to an address, so callers get a ready-made receive/change address
+//! generator instead of hand-rolling the index loop. `scan` additionally implements the usual
+//! gap-limit discovery algorithm: keep deriving until `gap_limit` consecutive addresses in a row
+//! come back unused.
+
+use bip32::{curve::model::Secp256k1Backend, derived::GenericDerivedXPub, path::KeyDerivation, Bip32Error};
+
+use crate::enc::AddressEncoder;
+
+/// The default gap limit used by `AccountScanner::new`, matching the de-facto wallet standard.
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Which BIP44 chain an address belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chain {
+    /// The receiving chain, `<account>/0/i`.
+    External,
+    /// The change chain, `<account>/1/i`.
+    Internal,
+}
+
+impl Chain {
+    fn index(self) -> u32 {
+        match self {
+            Chain::External => 0,
+            Chain::Internal => 1,
+        }
+    }
+}
+
+/// A single discovered address, with the full derivation that produced it so a later signing
+/// pass can match it back to PSBT inputs.
+#[derive(Clone, Debug)]
+pub struct DiscoveredAddress<A> {
+    /// The derivation, rooted at the wallet's master key, that produced this address.
+    pub derivation: KeyDerivation,
+    /// The encoded address itself.
+    pub address: A,
+}
+
+/// Errors arising while scanning an account for used addresses.
+#[derive(Debug)]
+pub enum ScanError<EncErr> {
+    /// Bubbled up from key derivation.
+    Bip32(Bip32Error),
+    /// Bubbled up from the `AddressEncoder`.
+    Encoding(EncErr),
+}
+
+impl<EncErr> From<Bip32Error> for ScanError<EncErr> {
+    fn from(e: Bip32Error) -> Self {
+        ScanError::Bip32(e)
+    }
+}
+
+/// Lazily derives every address down one BIP44 chain of an account xpub, encoding each child as
+/// it's produced.
+pub struct ChainAddresses<'a, T: Secp256k1Backend<'a>, E: AddressEncoder> {
+    account_xpub: GenericDerivedXPub<'a, T>,
+    chain: Chain,
+    next_index: u32,
+    to_recipient: Box<dyn Fn(&GenericDerivedXPub<'a, T>) -> E::RecipientIdentifier + 'a>,
+}
+
+impl<'a, T, E> Iterator for ChainAddresses<'a, T, E>
+where
+    T: Secp256k1Backend<'a>,
+    E: AddressEncoder,
+{
+    type Item = Result<DiscoveredAddress<E::Address>, ScanError<E::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next_index;
+        self.next_index += 1;
+
+        let result = self
+            .account_xpub
+            .derive_public_path(&[self.chain.index(), index])
+            .map_err(ScanError::Bip32)
+            .and_then(|child| {
+                let recipient = (self.to_recipient)(&child);
+                E::encode_address(recipient)
+                    .map(|address| DiscoveredAddress {
+                        derivation: child.derivation.clone(),
+                        address,
+                    })
+                    .map_err(ScanError::Encoding)
+            });
+
+        Some(result)
+    }
+}
+
+/// Scans an account xpub's external and internal chains for addresses with funding history,
+/// stopping each chain after a run of consecutive unused indices (the "gap limit").
+pub struct AccountScanner<'a, T: Secp256k1Backend<'a>> {
+    account_xpub: GenericDerivedXPub<'a, T>,
+    gap_limit: u32,
+}
+
+impl<'a, T: Secp256k1Backend<'a>> AccountScanner<'a, T> {
+    /// Build a scanner with the default gap limit of 20.
+    pub fn new(account_xpub: GenericDerivedXPub<'a, T>) -> Self {
+        Self {
+            account_xpub,
+            gap_limit: DEFAULT_GAP_LIMIT,
+        }
+    }
+
+    /// Build a scanner with a custom gap limit.
+    pub fn with_gap_limit(account_xpub: GenericDerivedXPub<'a, T>, gap_limit: u32) -> Self {
+        Self {
+            account_xpub,
+            gap_limit,
+        }
+    }
+
+    /// An unbounded iterator over every address on `chain`, in index order. Callers who don't
+    /// want gap-limit discovery (e.g. pre-generating a batch of receive addresses) can use this
+    /// directly; `scan` builds gap-limit discovery on top of it.
+    pub fn addresses<E: AddressEncoder>(
+        &self,
+        chain: Chain,
+        to_recipient: impl Fn(&GenericDerivedXPub<'a, T>) -> E::RecipientIdentifier + 'a,
+    ) -> ChainAddresses<'a, T, E> {
+        ChainAddresses {
+            account_xpub: self.account_xpub.clone(),
+            chain,
+            next_index: 0,
+            to_recipient: Box::new(to_recipient),
+        }
+    }
+
+    /// Derive addresses on `chain` until `gap_limit` consecutive indices in a row are reported
+    /// unused by `is_used`, returning every address that was found to be used.
+    pub fn scan<E: AddressEncoder>(
+        &self,
+        chain: Chain,
+        to_recipient: impl Fn(&GenericDerivedXPub<'a, T>) -> E::RecipientIdentifier + 'a,
+        mut is_used: impl FnMut(&E::Address) -> bool,
+    ) -> Result<Vec<DiscoveredAddress<E::Address>>, ScanError<E::Error>> {
+        let mut found = vec![];
+        let mut consecutive_unused = 0;
+
+        for item in self.addresses::<E>(chain, to_recipient) {
+            let discovered = item?;
+            if is_used(&discovered.address) {
+                consecutive_unused = 0;
+                found.push(discovered);
+            } else {
+                consecutive_unused += 1;
+                if consecutive_unused >= self.gap_limit {
+                    break;
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}